@@ -30,9 +30,141 @@ pub struct Message {
 #[derive(Deserialize)]
 pub struct InferRequest {
     pub messages: Vec<Message>,
+    #[serde(flatten, default)]
+    pub sampling: SamplingParams,
+    /// When set, reuses the cached KV-cache context for this id instead of rebuilding it.
+    #[serde(default)]
+    pub session_id: Option<String>,
 }
 
 #[derive(Serialize)]
 pub struct InferResponse {
     pub generated_text: String,
 }
+
+/// Why generation stopped, mirrored from the OpenAI chat-completions schema.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum FinishReason {
+    Stop,
+    Length,
+}
+
+/// Controls how the next token is picked from the model's logits each generation step.
+/// `seed` is recorded (rather than always drawn fresh) so a sampled response can be reproduced.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SamplingParams {
+    #[serde(default = "SamplingParams::default_temperature")]
+    pub temperature: f32,
+    #[serde(default = "SamplingParams::default_top_p")]
+    pub top_p: f32,
+    #[serde(default = "SamplingParams::default_top_k")]
+    pub top_k: usize,
+    #[serde(default = "SamplingParams::default_repeat_penalty")]
+    pub repeat_penalty: f32,
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// Caps how many tokens this request may generate. Never raises the server-wide
+    /// `GenerationConfig::max_generation_tokens` ceiling, only lowers it.
+    #[serde(default)]
+    pub max_tokens: Option<i32>,
+}
+
+impl SamplingParams {
+    fn default_temperature() -> f32 {
+        0.8
+    }
+    fn default_top_p() -> f32 {
+        1.0
+    }
+    fn default_top_k() -> usize {
+        40
+    }
+    fn default_repeat_penalty() -> f32 {
+        1.1
+    }
+}
+
+impl Default for SamplingParams {
+    fn default() -> Self {
+        Self {
+            temperature: Self::default_temperature(),
+            top_p: Self::default_top_p(),
+            top_k: Self::default_top_k(),
+            repeat_penalty: Self::default_repeat_penalty(),
+            seed: None,
+            max_tokens: None,
+        }
+    }
+}
+
+/* ---------- OpenAI-compatible `/v1/chat/completions` ---------- */
+
+#[derive(Serialize, Deserialize)]
+pub struct ChatCompletionRequest {
+    pub model: String,
+    pub messages: Vec<Message>,
+    /// Flattened alongside the other sampling knobs so `max_tokens` is just another field of
+    /// the wire-level JSON, not a separate top-level one.
+    #[serde(flatten, default)]
+    pub sampling: SamplingParams,
+    #[serde(default)]
+    pub stream: bool,
+    /// When set, reuses the cached KV-cache context for this id instead of rebuilding it.
+    #[serde(default)]
+    pub session_id: Option<String>,
+    /// When non-empty, runs each conversation independently and returns one choice per item
+    /// (indexed in order), instead of treating `messages` as a single conversation. Capped by
+    /// the server's `max_client_batch_size`; streaming is not supported for batch requests.
+    #[serde(default)]
+    pub batch: Vec<Vec<Message>>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Usage {
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+    pub total_tokens: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ChatCompletionChoice {
+    pub index: usize,
+    pub message: Message,
+    pub finish_reason: FinishReason,
+}
+
+// `object` and `model` are plain `String`s (rather than `&'static str`) so this struct can also
+// be deserialized when a `TransformBackend` proxies to a remote OpenAI-compatible server.
+#[derive(Serialize, Deserialize)]
+pub struct ChatCompletionResponse {
+    pub id: String,
+    pub object: String,
+    pub model: String,
+    pub choices: Vec<ChatCompletionChoice>,
+    pub usage: Usage,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct ChatCompletionChunkDelta {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<Role>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ChatCompletionChunkChoice {
+    pub index: usize,
+    pub delta: ChatCompletionChunkDelta,
+    pub finish_reason: Option<FinishReason>,
+}
+
+/// One SSE `data:` frame of a streamed chat completion.
+#[derive(Serialize, Deserialize)]
+pub struct ChatCompletionChunk {
+    pub id: String,
+    pub object: String,
+    pub model: String,
+    pub choices: Vec<ChatCompletionChunkChoice>,
+}
@@ -0,0 +1,119 @@
+// src/backend/llama_cpp.rs
+
+use super::TransformBackend;
+use crate::api::{Message, SamplingParams};
+use crate::configuration::{GenerationConfig, LlamaCppSettings};
+use crate::error::ServiceError;
+use crate::services::{self, InferenceOutput};
+use crate::session::SessionStore;
+use crate::template::ChatTemplate;
+use llama_cpp_2::{
+    llama_backend::LlamaBackend,
+    model::{params::LlamaModelParams, LlamaModel, Special},
+};
+use std::sync::Arc;
+
+/// Runs generation locally against a GGUF model loaded with `llama_cpp_2`.
+pub struct LlamaCppBackend {
+    backend: Arc<LlamaBackend>,
+    model: Arc<LlamaModel>,
+    template: ChatTemplate,
+    bos_token: String,
+    eos_token: String,
+    generation: GenerationConfig,
+    sessions: SessionStore,
+}
+
+impl LlamaCppBackend {
+    /// Loads the GGUF model described by `settings` and compiles its chat template —
+    /// `settings.chat_template_path` if set, otherwise the built-in ChatML template — sizing
+    /// every context (including cached sessions) per `generation`.
+    pub fn load(
+        settings: &LlamaCppSettings,
+        generation: GenerationConfig,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let backend = Arc::new(LlamaBackend::init()?);
+
+        let model_params = LlamaModelParams::default().with_n_gpu_layers(settings.n_gpu_layers);
+        let model = Arc::new(LlamaModel::load_from_file(
+            &backend,
+            &settings.model_path,
+            &model_params,
+        )?);
+
+        let bos_token = token_to_string(&model, model.token_bos())?;
+        let eos_token = token_to_string(&model, model.token_eos())?;
+        let template = match &settings.chat_template_path {
+            Some(path) => ChatTemplate::compile(std::fs::read_to_string(path)?)?,
+            None => ChatTemplate::compile_chatml()?,
+        };
+        let max_sessions = generation.max_sessions;
+        let sessions = SessionStore::new(
+            Arc::clone(&backend),
+            Arc::clone(&model),
+            generation.clone(),
+            max_sessions,
+        );
+
+        Ok(Self {
+            backend,
+            model,
+            template,
+            bos_token,
+            eos_token,
+            generation,
+            sessions,
+        })
+    }
+}
+
+impl TransformBackend for LlamaCppBackend {
+    fn do_completion(
+        &self,
+        messages: Vec<Message>,
+        sampling: SamplingParams,
+        session_id: Option<&str>,
+    ) -> Result<InferenceOutput, ServiceError> {
+        self.do_generate_stream(messages, sampling, session_id, &mut |_token| {})
+    }
+
+    fn do_generate_stream(
+        &self,
+        messages: Vec<Message>,
+        sampling: SamplingParams,
+        session_id: Option<&str>,
+        on_token: &mut dyn FnMut(&str),
+    ) -> Result<InferenceOutput, ServiceError> {
+        match session_id {
+            Some(session_id) => self.sessions.run_inference(
+                session_id,
+                messages,
+                &self.template,
+                &self.bos_token,
+                &self.eos_token,
+                sampling,
+                on_token,
+            ),
+            None => services::run_inference(
+                &self.backend,
+                &self.model,
+                &self.template,
+                &self.bos_token,
+                &self.eos_token,
+                &self.generation,
+                messages,
+                sampling,
+                on_token,
+            ),
+        }
+    }
+}
+
+/// Renders a special token (e.g. BOS/EOS) to the string the chat template expects to see.
+fn token_to_string(
+    model: &LlamaModel,
+    token: llama_cpp_2::token::LlamaToken,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let bytes = model.token_to_bytes(token, Special::Tokenize)?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
@@ -0,0 +1,30 @@
+// src/backend/mod.rs
+
+pub mod llama_cpp;
+pub mod remote;
+
+use super::api::{Message, SamplingParams};
+use super::error::ServiceError;
+use super::services::InferenceOutput;
+
+/// A model-serving backend the API routes requests to. `llama_cpp::LlamaCppBackend` runs a
+/// local GGUF model; `remote::RemoteBackend` proxies to an external OpenAI-compatible server.
+/// `AppState` holds one as a trait object, so the front-end API isn't tied to a single engine.
+pub trait TransformBackend: Send + Sync {
+    /// Runs one turn to completion and returns the full response.
+    fn do_completion(
+        &self,
+        messages: Vec<Message>,
+        sampling: SamplingParams,
+        session_id: Option<&str>,
+    ) -> Result<InferenceOutput, ServiceError>;
+
+    /// Runs one turn, invoking `on_token` with each piece of text as it's produced.
+    fn do_generate_stream(
+        &self,
+        messages: Vec<Message>,
+        sampling: SamplingParams,
+        session_id: Option<&str>,
+        on_token: &mut dyn FnMut(&str),
+    ) -> Result<InferenceOutput, ServiceError>;
+}
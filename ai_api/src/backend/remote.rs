@@ -0,0 +1,130 @@
+// src/backend/remote.rs
+
+use super::TransformBackend;
+use crate::api::{
+    ChatCompletionChunk, ChatCompletionRequest, ChatCompletionResponse, FinishReason, Message,
+    SamplingParams,
+};
+use crate::error::ServiceError;
+use crate::services::InferenceOutput;
+use std::io::{BufRead, BufReader};
+
+/// Proxies generation to a remote OpenAI-compatible `/v1/chat/completions` endpoint, so the
+/// same front-end API can be backed by a hosted model instead of a local GGUF file.
+pub struct RemoteBackend {
+    client: reqwest::blocking::Client,
+    host: String,
+    api_key: Option<String>,
+    model: String,
+}
+
+impl RemoteBackend {
+    /// `host` is the base URL of the remote server (e.g. `https://api.openai.com`); `api_key`,
+    /// if set, is sent as a `Bearer` token; `model` is the model name forwarded upstream.
+    pub fn new(host: String, api_key: Option<String>, model: String) -> Self {
+        Self {
+            client: reqwest::blocking::Client::new(),
+            host,
+            api_key,
+            model,
+        }
+    }
+
+    fn request(
+        &self,
+        messages: &[Message],
+        sampling: &SamplingParams,
+        stream: bool,
+    ) -> Result<reqwest::blocking::Response, ServiceError> {
+        let url = format!(
+            "{}/v1/chat/completions",
+            self.host.trim_end_matches('/')
+        );
+        let body = ChatCompletionRequest {
+            model: self.model.clone(),
+            messages: messages.to_vec(),
+            sampling: sampling.clone(),
+            stream,
+            session_id: None,
+            batch: Vec::new(),
+        };
+
+        let mut request = self.client.post(url).json(&body);
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+
+        request
+            .send()
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| ServiceError::RemoteBackend(e.to_string()))
+    }
+}
+
+impl TransformBackend for RemoteBackend {
+    fn do_completion(
+        &self,
+        messages: Vec<Message>,
+        sampling: SamplingParams,
+        _session_id: Option<&str>,
+    ) -> Result<InferenceOutput, ServiceError> {
+        let response = self.request(&messages, &sampling, false)?;
+        let body: ChatCompletionResponse = response
+            .json()
+            .map_err(|e| ServiceError::RemoteBackend(e.to_string()))?;
+        let choice = body.choices.into_iter().next().ok_or_else(|| {
+            ServiceError::RemoteBackend("remote backend returned no choices".to_string())
+        })?;
+
+        Ok(InferenceOutput {
+            text: choice.message.content,
+            prompt_tokens: body.usage.prompt_tokens,
+            completion_tokens: body.usage.completion_tokens,
+            finish_reason: choice.finish_reason,
+        })
+    }
+
+    fn do_generate_stream(
+        &self,
+        messages: Vec<Message>,
+        sampling: SamplingParams,
+        _session_id: Option<&str>,
+        on_token: &mut dyn FnMut(&str),
+    ) -> Result<InferenceOutput, ServiceError> {
+        let response = self.request(&messages, &sampling, true)?;
+
+        let mut text = String::new();
+        let mut completion_tokens = 0usize;
+        let mut finish_reason = FinishReason::Stop;
+
+        for line in BufReader::new(response).lines() {
+            let line = line.map_err(|e| ServiceError::RemoteBackend(e.to_string()))?;
+            let Some(payload) = line.strip_prefix("data: ") else {
+                continue;
+            };
+            if payload == "[DONE]" {
+                break;
+            }
+
+            let chunk: ChatCompletionChunk = serde_json::from_str(payload)
+                .map_err(|e| ServiceError::RemoteBackend(e.to_string()))?;
+            for choice in chunk.choices {
+                if let Some(content) = choice.delta.content {
+                    on_token(&content);
+                    text.push_str(&content);
+                    completion_tokens += 1;
+                }
+                if let Some(reason) = choice.finish_reason {
+                    finish_reason = reason;
+                }
+            }
+        }
+
+        Ok(InferenceOutput {
+            text,
+            prompt_tokens: 0,
+            completion_tokens,
+            finish_reason,
+        })
+    }
+}
@@ -0,0 +1,190 @@
+// src/configuration.rs
+
+use serde::Deserialize;
+use std::path::Path;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("failed to read config file {path}: {source}")]
+    Read {
+        path: String,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("failed to parse config file {path}: {source}")]
+    Parse {
+        path: String,
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+
+    #[error("invalid configuration: {0}")]
+    Invalid(String),
+}
+
+/// Generation limits and context sizing shared by every request, regardless of backend.
+#[derive(Deserialize, Clone)]
+pub struct GenerationConfig {
+    #[serde(default = "GenerationConfig::default_n_ctx")]
+    pub n_ctx: u32,
+    #[serde(default = "GenerationConfig::default_n_batch")]
+    pub n_batch: u32,
+    #[serde(default = "GenerationConfig::default_max_generation_tokens")]
+    pub max_generation_tokens: i32,
+    /// Maximum number of concurrent KV-cache sessions kept alive by the session store, beyond
+    /// which the least-recently-used one is evicted.
+    #[serde(default = "GenerationConfig::default_max_sessions")]
+    pub max_sessions: usize,
+}
+
+impl GenerationConfig {
+    // macOS boxes in this fleet are usually dev laptops with far less RAM to spare for
+    // the KV cache than the Linux inference boxes, so default to a smaller context there.
+    #[cfg(target_os = "macos")]
+    fn default_n_ctx() -> u32 {
+        2048
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn default_n_ctx() -> u32 {
+        4096
+    }
+
+    fn default_n_batch() -> u32 {
+        512
+    }
+
+    fn default_max_generation_tokens() -> i32 {
+        4096
+    }
+
+    fn default_max_sessions() -> usize {
+        32
+    }
+}
+
+impl Default for GenerationConfig {
+    fn default() -> Self {
+        Self {
+            n_ctx: Self::default_n_ctx(),
+            n_batch: Self::default_n_batch(),
+            max_generation_tokens: Self::default_max_generation_tokens(),
+            max_sessions: Self::default_max_sessions(),
+        }
+    }
+}
+
+/// Settings specific to running a local GGUF model through `llama_cpp_2`.
+#[derive(Deserialize)]
+pub struct LlamaCppSettings {
+    pub model_path: String,
+    #[serde(default)]
+    pub n_gpu_layers: u32,
+    /// Path to a Jinja/minijinja chat-prompt template file for this model (Llama, Mistral,
+    /// ChatML, Qwen, ...). Falls back to the built-in ChatML template when unset.
+    #[serde(default)]
+    pub chat_template_path: Option<String>,
+}
+
+/// Settings for proxying to a remote OpenAI-compatible server instead of loading a local model.
+#[derive(Deserialize)]
+pub struct RemoteSettings {
+    pub host: String,
+    #[serde(default)]
+    pub api_key: Option<String>,
+    pub model: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendSettings {
+    LlamaCpp(LlamaCppSettings),
+    Remote(RemoteSettings),
+}
+
+#[derive(Deserialize)]
+pub struct Configuration {
+    pub backend: BackendSettings,
+    #[serde(default)]
+    pub generation: GenerationConfig,
+    #[serde(default = "Configuration::default_bind_address")]
+    pub bind_address: String,
+    #[serde(default = "Configuration::default_port")]
+    pub port: u16,
+    #[serde(default = "Configuration::default_cors_origin")]
+    pub cors_origin: String,
+    /// Caps how many independent conversations a single batch request may contain, so one
+    /// client can't monopolize the blocking inference thread.
+    #[serde(default = "Configuration::default_max_client_batch_size")]
+    pub max_client_batch_size: usize,
+}
+
+impl Configuration {
+    fn default_bind_address() -> String {
+        "127.0.0.1".to_string()
+    }
+
+    fn default_port() -> u16 {
+        8080
+    }
+
+    fn default_cors_origin() -> String {
+        "http://localhost:3000".to_string()
+    }
+
+    fn default_max_client_batch_size() -> usize {
+        4
+    }
+
+    /// Loads configuration from `path`: parsed as JSON if it ends in `.json`, as TOML otherwise.
+    pub fn load(path: &str) -> Result<Self, ConfigError> {
+        let contents = std::fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_string(),
+            source,
+        })?;
+
+        let config = match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("json") => serde_json::from_str(&contents).map_err(|e| ConfigError::Parse {
+                path: path.to_string(),
+                source: Box::new(e),
+            })?,
+            _ => toml::from_str(&contents).map_err(|e| ConfigError::Parse {
+                path: path.to_string(),
+                source: Box::new(e),
+            })?,
+        };
+
+        Self::validate(config)
+    }
+
+    /// Resolves the config path from the first CLI argument, falling back to `CONFIG_FILE`,
+    /// then loads and validates it.
+    pub fn load_from_env_or_args() -> Result<Self, ConfigError> {
+        let path = std::env::args()
+            .nth(1)
+            .or_else(|| std::env::var("CONFIG_FILE").ok())
+            .ok_or_else(|| {
+                ConfigError::Invalid(
+                    "no config file given: pass it as the first CLI argument or set CONFIG_FILE"
+                        .to_string(),
+                )
+            })?;
+        Self::load(&path)
+    }
+
+    fn validate(self) -> Result<Self, ConfigError> {
+        match &self.backend {
+            BackendSettings::LlamaCpp(settings) if settings.model_path.trim().is_empty() => {
+                Err(ConfigError::Invalid(
+                    "backend.llama_cpp.model_path must not be empty".to_string(),
+                ))
+            }
+            BackendSettings::Remote(settings) if settings.host.trim().is_empty() => Err(
+                ConfigError::Invalid("backend.remote.host must not be empty".to_string()),
+            ),
+            _ => Ok(self),
+        }
+    }
+}
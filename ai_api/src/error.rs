@@ -17,6 +17,18 @@ pub enum ServiceError {
     #[error("Failed to process token: {0}")]
     LlamaTokenProcess(String),
 
+    #[error("Failed to compile chat template: {0}")]
+    TemplateCompile(String),
+
+    #[error("Failed to render chat template: {0}")]
+    TemplateRender(String),
+
+    #[error("Remote backend request failed: {0}")]
+    RemoteBackend(String),
+
+    #[error("{0}")]
+    InvalidRequest(String),
+
     #[error("Internal Server Error")]
     InternalError,
 }
@@ -24,7 +36,10 @@ pub enum ServiceError {
 // Allow Actix to convert our custom error into an HTTP response
 impl ResponseError for ServiceError {
     fn status_code(&self) -> StatusCode {
-        StatusCode::INTERNAL_SERVER_ERROR
+        match self {
+            ServiceError::InvalidRequest(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
     }
 
     fn error_response(&self) -> HttpResponse {
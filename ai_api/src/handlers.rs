@@ -1,10 +1,25 @@
 // src/handlers.rs
 
-use super::api::{InferRequest, InferResponse};
+use super::api::{
+    ChatCompletionChoice, ChatCompletionChunk, ChatCompletionChunkChoice,
+    ChatCompletionChunkDelta, ChatCompletionRequest, ChatCompletionResponse, InferRequest,
+    InferResponse, Message, Role, SamplingParams, Usage,
+};
 use super::error::ServiceError;
-use super::services;
 use super::AppState;
 use actix_web::{post, web, HttpResponse, Responder};
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+
+/// Generates an id of the form `chatcmpl-<unix-nanos>`, good enough to correlate
+/// the chunks of one streamed response without pulling in a UUID dependency.
+fn chat_completion_id() -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    format!("chatcmpl-{nanos}")
+}
 
 #[post("/infer")]
 pub async fn infer(
@@ -13,13 +28,173 @@ pub async fn infer(
 ) -> Result<impl Responder, ServiceError> {
     // Move the inference logic to a blocking thread, as it's CPU-intensive.
     let result = web::block(move || {
-        let messages = body.into_inner().messages;
-        services::run_inference(&state, messages)
+        let InferRequest {
+            messages,
+            sampling,
+            session_id,
+        } = body.into_inner();
+        state
+            .backend
+            .do_completion(messages, sampling, session_id.as_deref())
     })
     .await
     .map_err(|_e| ServiceError::InternalError)??; // First ? handles web::block error, second ? handles ServiceError
 
     Ok(HttpResponse::Ok().json(InferResponse {
-        generated_text: result,
+        generated_text: result.text,
+    }))
+}
+
+/// OpenAI-compatible `/v1/chat/completions`, so existing client libraries can talk to this
+/// server unchanged. Set `stream: true` to receive an SSE `text/event-stream` of token deltas
+/// instead of a single JSON body.
+#[post("/v1/chat/completions")]
+pub async fn chat_completions(
+    state: web::Data<AppState>,
+    body: web::Json<ChatCompletionRequest>,
+) -> Result<HttpResponse, ServiceError> {
+    let request = body.into_inner();
+    let model = request.model;
+    let messages = request.messages;
+    let sampling = request.sampling;
+    let session_id = request.session_id;
+    let batch = request.batch;
+
+    if !batch.is_empty() {
+        if request.stream {
+            return Err(ServiceError::InvalidRequest(
+                "batch requests do not support streaming".to_string(),
+            ));
+        }
+        return batch_chat_completions(state, model, sampling, batch).await;
+    }
+
+    if request.stream {
+        let (tx, rx) = mpsc::channel::<String>(32);
+        let stream_state = state.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut on_token = |token: &str| {
+                let _ = tx.blocking_send(token.to_string());
+            };
+            let result = stream_state.backend.do_generate_stream(
+                messages,
+                sampling,
+                session_id.as_deref(),
+                &mut on_token,
+            );
+            if let Err(e) = result {
+                eprintln!("chat completion stream failed: {e}");
+            }
+        });
+
+        let id = chat_completion_id();
+        let token_stream = ReceiverStream::new(rx).map(move |token| {
+            let chunk = ChatCompletionChunk {
+                id: id.clone(),
+                object: "chat.completion.chunk".to_string(),
+                model: model.clone(),
+                choices: vec![ChatCompletionChunkChoice {
+                    index: 0,
+                    delta: ChatCompletionChunkDelta {
+                        role: None,
+                        content: Some(token),
+                    },
+                    finish_reason: None,
+                }],
+            };
+            let payload = serde_json::to_string(&chunk).unwrap_or_default();
+            Ok::<_, actix_web::Error>(web::Bytes::from(format!("data: {payload}\n\n")))
+        });
+        let sse_stream =
+            token_stream.chain(tokio_stream::once(Ok(web::Bytes::from_static(b"data: [DONE]\n\n"))));
+
+        return Ok(HttpResponse::Ok()
+            .content_type("text/event-stream")
+            .streaming(sse_stream));
+    }
+
+    let result = web::block(move || {
+        state
+            .backend
+            .do_completion(messages, sampling, session_id.as_deref())
+    })
+    .await
+    .map_err(|_e| ServiceError::InternalError)??;
+
+    Ok(HttpResponse::Ok().json(ChatCompletionResponse {
+        id: chat_completion_id(),
+        object: "chat.completion".to_string(),
+        model,
+        choices: vec![ChatCompletionChoice {
+            index: 0,
+            message: Message {
+                role: Role::Assistant,
+                content: result.text,
+            },
+            finish_reason: result.finish_reason,
+        }],
+        usage: Usage {
+            prompt_tokens: result.prompt_tokens,
+            completion_tokens: result.completion_tokens,
+            total_tokens: result.prompt_tokens + result.completion_tokens,
+        },
+    }))
+}
+
+/// Runs each conversation in `batch` independently on the blocking thread pool and returns
+/// one choice per item, indexed in request order. Rejects the batch outright (422) if it
+/// exceeds `state.max_client_batch_size`, rather than silently truncating it.
+async fn batch_chat_completions(
+    state: web::Data<AppState>,
+    model: String,
+    sampling: SamplingParams,
+    batch: Vec<Vec<Message>>,
+) -> Result<HttpResponse, ServiceError> {
+    if batch.len() > state.max_client_batch_size {
+        return Err(ServiceError::InvalidRequest(format!(
+            "batch of {} conversations exceeds max_client_batch_size of {}",
+            batch.len(),
+            state.max_client_batch_size
+        )));
+    }
+
+    let results = web::block(move || {
+        batch
+            .into_iter()
+            .map(|messages| state.backend.do_completion(messages, sampling.clone(), None))
+            .collect::<Result<Vec<_>, _>>()
+    })
+    .await
+    .map_err(|_e| ServiceError::InternalError)??;
+
+    let mut prompt_tokens = 0;
+    let mut completion_tokens = 0;
+    let choices = results
+        .into_iter()
+        .enumerate()
+        .map(|(index, result)| {
+            prompt_tokens += result.prompt_tokens;
+            completion_tokens += result.completion_tokens;
+            ChatCompletionChoice {
+                index,
+                message: Message {
+                    role: Role::Assistant,
+                    content: result.text,
+                },
+                finish_reason: result.finish_reason,
+            }
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(ChatCompletionResponse {
+        id: chat_completion_id(),
+        object: "chat.completion".to_string(),
+        model,
+        choices,
+        usage: Usage {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+        },
     }))
-}
\ No newline at end of file
+}
@@ -1,30 +1,19 @@
 // src/services.rs
 
-use super::api::Message;
+use super::api::{FinishReason, Message, SamplingParams};
+use super::configuration::GenerationConfig;
 use super::error::ServiceError;
-use super::AppState;
+use super::template::ChatTemplate;
 use llama_cpp_2::{
-    context::params::LlamaContextParams,
+    context::{params::LlamaContextParams, LlamaContext},
+    llama_backend::LlamaBackend,
     llama_batch::LlamaBatch,
-    model::{AddBos, Special},
+    model::{AddBos, LlamaModel, Special},
     token::LlamaToken,
 };
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use std::num::NonZeroU32;
 
-/// Formats a conversation history into a single prompt string for the Qwen model.
-fn build_prompt_from_messages(messages: &[Message]) -> String {
-    let mut prompt = String::new();
-    for message in messages {
-        let turn = format!(
-            "<|im_start|>{}\n{}<|im_end|>\n",
-            message.role, message.content
-        );
-        prompt.push_str(&turn);
-    }
-    prompt.push_str("<|im_start|>assistant\n");
-    prompt
-}
-
 /// Parses the model's raw output, logs any "thoughts", and returns the clean answer.
 fn parse_and_log_thoughts(raw_output: String) -> String {
     match raw_output.rfind("</think>") {
@@ -42,59 +31,253 @@ fn parse_and_log_thoughts(raw_output: String) -> String {
     }
 }
 
-pub fn run_inference(state: &AppState, messages: Vec<Message>) -> Result<String, ServiceError> {
-    const MAX_NEW_TOKENS: i32 = 4096;
-    let model = &state.model;
+const THINK_OPEN: &str = "<think>";
+const THINK_CLOSE: &str = "</think>";
+
+/// Whether a streamed token is still inside (or might still turn out to be the start of) a
+/// leading `<think>...</think>` block.
+enum ThoughtState {
+    /// Not yet determined whether the response opens with a think block.
+    Sniffing,
+    /// Inside the think block; withholding tokens until `</think>` is seen.
+    Thinking,
+    /// Past any think block (or none existed); tokens are forwarded as they arrive.
+    Passthrough,
+}
+
+/// Wraps a raw per-token `on_token` callback so a leading `<think>...</think>` block is held
+/// back instead of streamed, mirroring how `parse_and_log_thoughts` strips it from the
+/// non-streaming response — without this, SSE clients would see the model's internal
+/// reasoning that non-streaming clients never get.
+struct ThoughtFilter<'a> {
+    on_token: &'a mut dyn FnMut(&str),
+    buffer: String,
+    state: ThoughtState,
+}
+
+impl<'a> ThoughtFilter<'a> {
+    fn new(on_token: &'a mut dyn FnMut(&str)) -> Self {
+        Self {
+            on_token,
+            buffer: String::new(),
+            state: ThoughtState::Sniffing,
+        }
+    }
+
+    fn push(&mut self, token: &str) {
+        if let ThoughtState::Passthrough = self.state {
+            (self.on_token)(token);
+            return;
+        }
+
+        self.buffer.push_str(token);
+
+        if let ThoughtState::Sniffing = self.state {
+            if THINK_OPEN.starts_with(self.buffer.as_str()) {
+                return; // Could still become "<think>"; keep buffering.
+            }
+            if self.buffer.starts_with(THINK_OPEN) {
+                self.buffer.drain(..THINK_OPEN.len());
+                self.state = ThoughtState::Thinking;
+            } else {
+                // Doesn't open with a think block at all; flush what we held and stop buffering.
+                self.state = ThoughtState::Passthrough;
+                let buffered = std::mem::take(&mut self.buffer);
+                (self.on_token)(&buffered);
+                return;
+            }
+        }
+
+        if let Some(end) = self.buffer.find(THINK_CLOSE) {
+            let after = self.buffer.split_off(end + THINK_CLOSE.len());
+            self.state = ThoughtState::Passthrough;
+            self.buffer.clear();
+            let trimmed = after.trim_start();
+            if !trimmed.is_empty() {
+                (self.on_token)(trimmed);
+            }
+        }
+    }
+
+    /// Forwards whatever is still buffered, as-is, once generation has stopped for good.
+    /// Without this, a response that's cut off before `</think>` closes (EOS never sampled,
+    /// or `max_new_tokens` exhausted mid-thought) would stream nothing at all, even though
+    /// the non-streaming path falls back to returning that same raw text unstripped.
+    fn flush(mut self) {
+        if matches!(self.state, ThoughtState::Passthrough) {
+            return;
+        }
+        let buffered = std::mem::take(&mut self.buffer);
+        if !buffered.is_empty() {
+            (self.on_token)(&buffered);
+        }
+    }
+}
+
+/// Result of a completed generation (streamed or not), including OpenAI-style token accounting.
+pub struct InferenceOutput {
+    pub text: String,
+    pub prompt_tokens: usize,
+    pub completion_tokens: usize,
+    pub finish_reason: FinishReason,
+}
+
+/// Caps `sampling.max_tokens` (if the client set one) at `generation.max_generation_tokens`,
+/// so a per-request limit can only tighten the server-wide ceiling, never loosen it.
+pub(crate) fn effective_max_tokens(sampling: &SamplingParams, generation: &GenerationConfig) -> i32 {
+    sampling
+        .max_tokens
+        .map(|max_tokens| max_tokens.clamp(1, generation.max_generation_tokens))
+        .unwrap_or(generation.max_generation_tokens)
+}
+
+/// Runs generation for `messages`, invoking `on_token` with each decoded token as it is produced,
+/// except that a leading `<think>...</think>` block is held back (see `ThoughtFilter`).
+///
+/// `on_token` is how callers observe the generation incrementally (e.g. to stream it over SSE);
+/// the final, post-processed answer is still returned as a whole once generation finishes.
+#[allow(clippy::too_many_arguments)]
+pub fn run_inference(
+    backend: &LlamaBackend,
+    model: &LlamaModel,
+    template: &ChatTemplate,
+    bos_token: &str,
+    eos_token: &str,
+    generation: &GenerationConfig,
+    messages: Vec<Message>,
+    sampling: SamplingParams,
+    on_token: impl FnMut(&str),
+) -> Result<InferenceOutput, ServiceError> {
+    // 1. Format the prompt using this model's chat template
+    let prompt_str = template.render(&messages, bos_token, eos_token)?;
 
-    // 1. Format the prompt
-    let prompt_str = build_prompt_from_messages(&messages);
+    // 2. Build a fresh context for this request
+    let mut ctx = new_context(model, backend, generation)?;
 
-    // 2. Build context
+    let max_new_tokens = effective_max_tokens(&sampling, generation);
+
+    // 3. Tokenize and feed the whole conversation in
+    let prompt_tokens = decode_prompt(model, &mut ctx, &prompt_str, 0, max_new_tokens as usize)?;
+
+    // 4. Generate the response
+    let result = generate_tokens(
+        model,
+        &mut ctx,
+        prompt_tokens as i32,
+        max_new_tokens,
+        &sampling,
+        on_token,
+    )?;
+
+    // 5. Post-process the output
+    let final_answer = parse_and_log_thoughts(result.text);
+
+    Ok(InferenceOutput {
+        text: final_answer,
+        prompt_tokens,
+        completion_tokens: result.completion_tokens,
+        finish_reason: result.finish_reason,
+    })
+}
+
+/// Builds a fresh `LlamaContext` sized per `generation`'s `n_ctx`/`n_batch`. Shared by the
+/// per-request path and the session store, which builds one context per cached session.
+pub(crate) fn new_context<'a>(
+    model: &'a LlamaModel,
+    backend: &LlamaBackend,
+    generation: &GenerationConfig,
+) -> Result<LlamaContext<'a>, ServiceError> {
     let ctx_params = LlamaContextParams::default()
-        .with_n_ctx(NonZeroU32::new(4096))
-        .with_n_batch(512);
-    let mut ctx = model
-        .new_context(&state.backend, ctx_params)
-        .map_err(|e| ServiceError::LlamaContext(e.to_string()))?;
+        .with_n_ctx(NonZeroU32::new(generation.n_ctx))
+        .with_n_batch(generation.n_batch);
+    model
+        .new_context(backend, ctx_params)
+        .map_err(|e| ServiceError::LlamaContext(e.to_string()))
+}
 
-    // 3. Tokenize and feed prompt
+/// Tokenizes `prompt` and decodes it into `ctx` starting at KV-cache position `start_pos`,
+/// returning how many tokens were fed in. Shared by the per-request path and the session
+/// store, which only has to decode the newly appended portion of a conversation.
+pub(crate) fn decode_prompt(
+    model: &LlamaModel,
+    ctx: &mut LlamaContext,
+    prompt: &str,
+    start_pos: i32,
+    max_new_tokens: usize,
+) -> Result<usize, ServiceError> {
     let toks = model
-        .str_to_token(&prompt_str, AddBos::Never)
+        .str_to_token(prompt, AddBos::Never)
         .map_err(|e| ServiceError::LlamaTokenize(e.to_string()))?;
-    let mut batch = LlamaBatch::new(toks.len() + (MAX_NEW_TOKENS as usize), 1);
+    let mut batch = LlamaBatch::new(toks.len() + max_new_tokens, 1);
     let last_idx = toks.len() as i32 - 1;
     for (i, t) in (0_i32..).zip(toks.iter()) {
-        batch.add(*t, i, &[0], i == last_idx).unwrap();
+        batch.add(*t, start_pos + i, &[0], i == last_idx).unwrap();
     }
     ctx.decode(&mut batch)
         .map_err(|e| ServiceError::LlamaDecode(e.to_string()))?;
+    Ok(toks.len())
+}
+
+/// Output of a single generation run: the raw (not yet thought-stripped) text plus accounting.
+pub(crate) struct GenerationResult {
+    pub text: String,
+    pub completion_tokens: usize,
+    pub finish_reason: FinishReason,
+    /// KV-cache position after the last generated token, for callers that keep `ctx` around.
+    pub end_pos: i32,
+}
 
-    // 4. Generate response tokens
+/// Samples tokens from `ctx` one at a time, starting at KV-cache position `pos`, until EOS or
+/// `max_new_tokens` is reached. Shared by the per-request path and the session store.
+pub(crate) fn generate_tokens(
+    model: &LlamaModel,
+    ctx: &mut LlamaContext,
+    mut pos: i32,
+    max_new_tokens: i32,
+    sampling: &SamplingParams,
+    mut on_token: impl FnMut(&str),
+) -> Result<GenerationResult, ServiceError> {
     let eos = model.token_eos();
     let mut out = String::new();
-    let mut pos = batch.n_tokens();
+    let mut completion_tokens = 0usize;
+    let mut finish_reason = FinishReason::Length;
+    let mut history: Vec<usize> = Vec::new();
+    let mut rng = match sampling.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let mut batch = LlamaBatch::new(1, 1);
+    let mut thought_filter = ThoughtFilter::new(&mut on_token);
 
-    for _ in 0..MAX_NEW_TOKENS {
+    for _ in 0..max_new_tokens {
         batch.clear();
-        let logits = ctx.get_logits();
-        // Simple greedy sampling
-        let next_id = LlamaToken::new(
+        let logits = ctx.get_logits().to_vec();
+        let next_token_id = if sampling.temperature == 0.0 {
+            // Greedy argmax, unchanged from before sampling was configurable.
             logits
                 .iter()
                 .enumerate()
                 .max_by(|(_, a), (_, b)| a.total_cmp(b))
-                .map(|(i, _)| i as i32)
-                .unwrap(),
-        );
+                .map(|(i, _)| i)
+                .unwrap()
+        } else {
+            sample_token(logits, &history, sampling, &mut rng)
+        };
+        history.push(next_token_id);
+        let next_id = LlamaToken::new(next_token_id as i32);
 
         if next_id == eos {
+            finish_reason = FinishReason::Stop;
             break;
         }
 
         let bytes = model
             .token_to_bytes(next_id, Special::Tokenize)
             .map_err(|e| ServiceError::LlamaTokenProcess(e.to_string()))?;
-        out.push_str(&String::from_utf8_lossy(&bytes));
+        let token_str = String::from_utf8_lossy(&bytes).into_owned();
+        thought_filter.push(&token_str);
+        out.push_str(&token_str);
+        completion_tokens += 1;
 
         batch.add(next_id, pos, &[0], true).unwrap();
         ctx.decode(&mut batch)
@@ -102,8 +285,74 @@ pub fn run_inference(state: &AppState, messages: Vec<Message>) -> Result<String,
         pos += 1;
     }
 
-    // 5. Post-process the output
-    let final_answer = parse_and_log_thoughts(out);
+    thought_filter.flush();
+
+    Ok(GenerationResult {
+        text: out,
+        completion_tokens,
+        finish_reason,
+        end_pos: pos,
+    })
+}
 
-    Ok(final_answer)
+/// Picks the next token id from `logits`, following the standard sampling pipeline:
+/// repetition penalty, temperature scaling, softmax, top-k filter, top-p (nucleus) filter,
+/// then a renormalized categorical draw from `rng`.
+fn sample_token(
+    mut logits: Vec<f32>,
+    history: &[usize],
+    params: &SamplingParams,
+    rng: &mut StdRng,
+) -> usize {
+    // 1. Repetition penalty: discourage tokens already seen in this generation.
+    for &id in history {
+        if let Some(logit) = logits.get_mut(id) {
+            *logit = if *logit > 0.0 {
+                *logit / params.repeat_penalty
+            } else {
+                *logit * params.repeat_penalty
+            };
+        }
+    }
+
+    // 2. Temperature.
+    for logit in logits.iter_mut() {
+        *logit /= params.temperature;
+    }
+
+    // 3. Softmax.
+    let max_logit = logits.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    let mut probs: Vec<f32> = logits.iter().map(|l| (l - max_logit).exp()).collect();
+    let sum: f32 = probs.iter().sum();
+    for p in probs.iter_mut() {
+        *p /= sum;
+    }
+
+    // 4. Top-k filter: keep only the k highest-probability tokens, sorted descending.
+    let mut ranked: Vec<usize> = (0..probs.len()).collect();
+    ranked.sort_unstable_by(|&a, &b| probs[b].total_cmp(&probs[a]));
+    ranked.truncate(params.top_k.max(1).min(ranked.len()));
+
+    // 5. Top-p (nucleus) filter: take the smallest prefix whose cumulative probability >= top_p.
+    let mut cumulative = 0.0;
+    let mut cutoff = ranked.len();
+    for (i, &id) in ranked.iter().enumerate() {
+        cumulative += probs[id];
+        if cumulative >= params.top_p {
+            cutoff = i + 1;
+            break;
+        }
+    }
+    ranked.truncate(cutoff.max(1));
+
+    // 6. Renormalize and sample.
+    let kept_sum: f32 = ranked.iter().map(|&id| probs[id]).sum();
+    let mut draw = rng.gen::<f32>() * kept_sum;
+    for &id in &ranked {
+        draw -= probs[id];
+        if draw <= 0.0 {
+            return id;
+        }
+    }
+    *ranked.last().unwrap()
 }
@@ -0,0 +1,185 @@
+// src/session.rs
+
+use super::api::{Message, Role, SamplingParams};
+use super::configuration::GenerationConfig;
+use super::error::ServiceError;
+use super::services::{self, InferenceOutput};
+use super::template::ChatTemplate;
+use llama_cpp_2::{context::LlamaContext, llama_backend::LlamaBackend, model::LlamaModel};
+use lru::LruCache;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+
+/// A cached conversation: the live KV-cache context plus how much of it has already been
+/// decoded, so a follow-up turn only has to ingest the messages appended since last time.
+struct Session {
+    ctx: LlamaContext<'static>,
+    /// KV-cache position already written into `ctx`.
+    pos: i32,
+    /// The exact message list already decoded into `ctx`, used to diff against the next turn.
+    prefix: Vec<Message>,
+}
+
+/// An LRU-evicted collection of live sessions, each guarded by its own mutex so concurrent
+/// requests for different `session_id`s don't serialize against each other.
+pub struct SessionStore {
+    backend: &'static LlamaBackend,
+    model: &'static LlamaModel,
+    generation: GenerationConfig,
+    sessions: Mutex<LruCache<String, Arc<Mutex<Session>>>>,
+}
+
+impl SessionStore {
+    /// `backend`/`model` already live for the lifetime of the server, so they're leaked once
+    /// here to promote them to `'static` — that lets a cached `LlamaContext` outlive any single
+    /// request without every `Session` having to carry its own backend/model handle.
+    pub fn new(
+        backend: Arc<LlamaBackend>,
+        model: Arc<LlamaModel>,
+        generation: GenerationConfig,
+        max_sessions: usize,
+    ) -> Self {
+        let backend: &'static LlamaBackend = Box::leak(Box::new(backend));
+        let model: &'static LlamaModel = Box::leak(Box::new(model));
+        Self {
+            backend,
+            model,
+            generation,
+            sessions: Mutex::new(LruCache::new(
+                NonZeroUsize::new(max_sessions.max(1)).unwrap(),
+            )),
+        }
+    }
+
+    /// Runs generation for `messages` against the session cached under `session_id`,
+    /// decoding only the messages appended since the last turn and reusing the rest of the
+    /// KV cache. Falls back to decoding the full conversation if `messages` doesn't extend
+    /// the cached prefix (e.g. the client edited or removed an earlier message).
+    pub fn run_inference(
+        &self,
+        session_id: &str,
+        messages: Vec<Message>,
+        template: &ChatTemplate,
+        bos_token: &str,
+        eos_token: &str,
+        sampling: SamplingParams,
+        on_token: impl FnMut(&str),
+    ) -> Result<InferenceOutput, ServiceError> {
+        let session = self.get_or_insert(session_id)?;
+        let mut session = session.lock().unwrap();
+
+        let common_len = messages
+            .iter()
+            .zip(session.prefix.iter())
+            .take_while(|(a, b)| a.role.to_string() == b.role.to_string() && a.content == b.content)
+            .count();
+
+        // Rendering just the suffix of appended messages only matches rendering the full
+        // conversation for templates whose per-message block doesn't depend on position (e.g.
+        // ChatML); a template that special-cases the first message or the system preamble
+        // would render a different prompt here, silently diverging the cache from the prompt
+        // it's supposed to hold. Fall back to a full re-decode for any other template.
+        let reusable = template.incremental_reuse_safe()
+            && common_len == session.prefix.len()
+            && common_len < messages.len();
+        let new_messages = if reusable {
+            &messages[common_len..]
+        } else {
+            session.ctx.clear_kv_cache();
+            session.pos = 0;
+            session.prefix.clear();
+            &messages[..]
+        };
+
+        let max_new_tokens = services::effective_max_tokens(&sampling, &self.generation);
+
+        let prompt_str = template.render(new_messages, bos_token, eos_token)?;
+        let prompt_tokens = services::decode_prompt(
+            self.model,
+            &mut session.ctx,
+            &prompt_str,
+            session.pos,
+            max_new_tokens as usize,
+        )?;
+        session.pos += prompt_tokens as i32;
+
+        let result = services::generate_tokens(
+            self.model,
+            &mut session.ctx,
+            session.pos,
+            max_new_tokens,
+            &sampling,
+            on_token,
+        )?;
+
+        // Generation stops as soon as EOS is sampled, so ChatML's closing delimiter
+        // (`<|im_end|>\n`) was never fed back into the cache. Decode it now so the KV cache
+        // ends exactly where the rendered prefix below says it does. This only applies to
+        // ChatML-shaped templates: it's only the cache of an `incremental_reuse_safe`
+        // template that a future turn will extend rather than fully re-decode, so it's the
+        // only one worth (and safe to) patch up here.
+        session.pos = if template.incremental_reuse_safe() {
+            let closing = "<|im_end|>\n";
+            let closing_tokens = services::decode_prompt(
+                self.model,
+                &mut session.ctx,
+                closing,
+                result.end_pos,
+                0,
+            )?;
+            result.end_pos + closing_tokens as i32
+        } else {
+            result.end_pos
+        };
+
+        let mut prefix = messages;
+        prefix.push(Message {
+            role: Role::Assistant,
+            content: result.text.clone(),
+        });
+        session.prefix = prefix;
+
+        // The cached prefix keeps the raw text (that is what the KV cache holds); the caller
+        // gets it with any leading `<think>...</think>` block stripped, as on the per-request path.
+        let text = match result.text.rfind("</think>") {
+            Some(end) => result.text[end + "</think>".len()..].trim_start().to_string(),
+            None => result.text,
+        };
+
+        Ok(InferenceOutput {
+            text,
+            prompt_tokens,
+            completion_tokens: result.completion_tokens,
+            finish_reason: result.finish_reason,
+        })
+    }
+
+    fn get_or_insert(&self, session_id: &str) -> Result<Arc<Mutex<Session>>, ServiceError> {
+        {
+            let mut sessions = self.sessions.lock().unwrap();
+            if let Some(session) = sessions.get(session_id) {
+                return Ok(Arc::clone(session));
+            }
+        }
+
+        // Build the new context with the global lock released, so concurrent first turns for
+        // different session ids don't serialize against each other for the full duration of
+        // an `n_ctx`-sized allocation.
+        let ctx = services::new_context(self.model, self.backend, &self.generation)?;
+        let session = Arc::new(Mutex::new(Session {
+            ctx,
+            pos: 0,
+            prefix: Vec::new(),
+        }));
+
+        let mut sessions = self.sessions.lock().unwrap();
+        // Another request for the same id may have raced us while the lock was released and
+        // already inserted its own context; prefer that one so both requests end up sharing a
+        // single session instead of silently diverging onto two different contexts.
+        if let Some(existing) = sessions.get(session_id) {
+            return Ok(Arc::clone(existing));
+        }
+        sessions.put(session_id.to_string(), Arc::clone(&session));
+        Ok(session)
+    }
+}
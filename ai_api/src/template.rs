@@ -0,0 +1,178 @@
+// src/template.rs
+
+use super::api::Message;
+use super::error::ServiceError;
+use minijinja::{context, Environment, Error as MiniJinjaError, ErrorKind};
+
+/// The Qwen ChatML format `build_prompt_from_messages` used to hardcode, now expressed as a
+/// template so it's just the default rather than the only option.
+pub const DEFAULT_CHATML_TEMPLATE: &str = "\
+{%- for message in messages -%}
+<|im_start|>{{ message.role }}
+{{ message.content }}<|im_end|>
+{% endfor -%}
+<|im_start|>assistant
+";
+
+const TEMPLATE_NAME: &str = "chat";
+
+/// A compiled chat-prompt template for one model family (Llama, Mistral, ChatML, Qwen, ...).
+///
+/// Rendering exposes `messages`, `bos_token`, `eos_token` as template variables, plus a
+/// `raise_exception(msg)` function the template can call to reject the conversation (e.g. an
+/// unsupported role ordering) instead of silently rendering something wrong.
+pub struct ChatTemplate {
+    env: Environment<'static>,
+    incremental_reuse_safe: bool,
+}
+
+impl ChatTemplate {
+    /// Compiles `source` (a minijinja/Jinja2 chat-template string) for later rendering. The
+    /// result is treated as unsafe for `SessionStore`'s incremental-render reuse, since an
+    /// arbitrary loaded template may branch on a message's position in the conversation (e.g.
+    /// a Llama/Mistral-style system preamble special-cased via `loop.first`), in which case
+    /// rendering just the suffix of appended messages wouldn't match rendering the full
+    /// conversation.
+    pub fn compile(source: String) -> Result<Self, ServiceError> {
+        Self::compile_with_reuse_safety(source, false)
+    }
+
+    /// Compiles the built-in ChatML template, whose per-message block is the same regardless
+    /// of position in the conversation, so rendering only the messages appended since the
+    /// last turn is safe for `SessionStore` to reuse.
+    pub fn compile_chatml() -> Result<Self, ServiceError> {
+        Self::compile_with_reuse_safety(DEFAULT_CHATML_TEMPLATE.to_string(), true)
+    }
+
+    fn compile_with_reuse_safety(
+        source: String,
+        incremental_reuse_safe: bool,
+    ) -> Result<Self, ServiceError> {
+        let mut env = Environment::new();
+        // Keep the template's trailing newline, e.g. ChatML's `<|im_start|>assistant\n`.
+        env.set_keep_trailing_newline(true);
+        env.add_function("raise_exception", raise_exception);
+        env.add_template_owned(TEMPLATE_NAME, source)
+            .map_err(|e| ServiceError::TemplateCompile(e.to_string()))?;
+        Ok(Self {
+            env,
+            incremental_reuse_safe,
+        })
+    }
+
+    /// Whether `SessionStore` may render just the suffix of messages appended since the last
+    /// turn instead of the full conversation, and keep extending the cached KV-cache prefix.
+    pub fn incremental_reuse_safe(&self) -> bool {
+        self.incremental_reuse_safe
+    }
+
+    /// Renders `messages` into a single prompt string, injecting `bos`/`eos` as the
+    /// `bos_token`/`eos_token` template variables.
+    pub fn render(
+        &self,
+        messages: &[Message],
+        bos: &str,
+        eos: &str,
+    ) -> Result<String, ServiceError> {
+        let template = self
+            .env
+            .get_template(TEMPLATE_NAME)
+            .map_err(|e| ServiceError::TemplateCompile(e.to_string()))?;
+        template
+            .render(context! { messages, bos_token => bos, eos_token => eos })
+            .map_err(|e| ServiceError::TemplateRender(e.to_string()))
+    }
+}
+
+/// The `raise_exception(msg)` helper callable from templates, mirroring the HuggingFace
+/// `transformers` chat-template convention for aborting on an unsupported conversation shape.
+fn raise_exception(msg: String) -> Result<String, MiniJinjaError> {
+    Err(MiniJinjaError::new(ErrorKind::InvalidOperation, msg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::Role;
+
+    fn messages() -> Vec<Message> {
+        vec![
+            Message {
+                role: Role::System,
+                content: "be helpful".to_string(),
+            },
+            Message {
+                role: Role::User,
+                content: "hi".to_string(),
+            },
+        ]
+    }
+
+    #[test]
+    fn renders_a_valid_template() {
+        let template = ChatTemplate::compile(DEFAULT_CHATML_TEMPLATE.to_string()).unwrap();
+        let prompt = template.render(&messages(), "<bos>", "<eos>").unwrap();
+
+        assert!(prompt.contains("<|im_start|>system\nbe helpful<|im_end|>"));
+        assert!(prompt.contains("<|im_start|>user\nhi<|im_end|>"));
+        assert!(prompt.ends_with("<|im_start|>assistant\n"));
+    }
+
+    #[test]
+    fn raise_exception_aborts_rendering() {
+        let source = "\
+{%- for message in messages -%}
+{%- if message.role == \"assistant\" and loop.first -%}
+{{ raise_exception(\"conversation cannot start with an assistant message\") }}
+{%- endif -%}
+{{ message.content }}
+{%- endfor -%}"
+            .to_string();
+        let template = ChatTemplate::compile(source).unwrap();
+
+        let err = template
+            .render(
+                &[Message {
+                    role: Role::Assistant,
+                    content: "hi".to_string(),
+                }],
+                "<bos>",
+                "<eos>",
+            )
+            .unwrap_err();
+
+        assert!(matches!(err, ServiceError::TemplateRender(_)));
+    }
+
+    #[test]
+    fn invalid_message_sequence_is_rejected() {
+        let source = "\
+{%- for message in messages -%}
+{%- if message.role == \"user\" and loop.previtem and loop.previtem.role == \"user\" -%}
+{{ raise_exception(\"two user messages cannot follow each other\") }}
+{%- endif -%}
+{{ message.content }}
+{%- endfor -%}"
+            .to_string();
+        let template = ChatTemplate::compile(source).unwrap();
+
+        let err = template
+            .render(
+                &[
+                    Message {
+                        role: Role::User,
+                        content: "first".to_string(),
+                    },
+                    Message {
+                        role: Role::User,
+                        content: "second".to_string(),
+                    },
+                ],
+                "<bos>",
+                "<eos>",
+            )
+            .unwrap_err();
+
+        assert!(matches!(err, ServiceError::TemplateRender(_)));
+    }
+}